@@ -0,0 +1,76 @@
+use std::error;
+use std::fmt;
+use std::io;
+
+/// An error encountered while reading or writing a plist.
+///
+/// Errors produced by a reader that can track its position in the input
+/// carry the byte offset at which the problem was detected; see
+/// [`position`](#method.position).
+#[derive(Debug)]
+pub struct Error {
+    kind: ErrorKind,
+    position: Option<u64>,
+}
+
+#[derive(Debug)]
+enum ErrorKind {
+    Io(io::Error),
+    InvalidData,
+}
+
+impl Error {
+    /// The input was not a valid plist of the expected format.
+    pub(crate) fn invalid_data() -> Error {
+        Error {
+            kind: ErrorKind::InvalidData,
+            position: None,
+        }
+    }
+
+    /// Tags this error with the byte offset at which it was detected, unless
+    /// it already carries one (e.g. from a reader closer to the failure).
+    pub(crate) fn with_position_if_unset(mut self, position: u64) -> Error {
+        if self.position.is_none() {
+            self.position = Some(position);
+        }
+        self
+    }
+
+    /// The byte offset into the input at which this error was detected, if
+    /// the reader that produced it was able to track its position.
+    pub fn position(&self) -> Option<u64> {
+        self.position
+    }
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            ErrorKind::Io(ref err) => write!(f, "{}", err)?,
+            ErrorKind::InvalidData => write!(f, "invalid plist data")?,
+        }
+        if let Some(position) = self.position {
+            write!(f, " at byte offset {}", position)?;
+        }
+        Ok(())
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        match self.kind {
+            ErrorKind::Io(ref err) => Some(err),
+            ErrorKind::InvalidData => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(err: io::Error) -> Error {
+        Error {
+            kind: ErrorKind::Io(err),
+            position: None,
+        }
+    }
+}
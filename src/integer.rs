@@ -0,0 +1,100 @@
+use std::fmt;
+
+/// An integer that can hold the full range of both `i64` and `u64`.
+///
+/// The binary format stores integers as 1/2/4/8 byte big-endian values; an
+/// 8-byte value may fall anywhere in `u64` and so cannot always be represented
+/// as an `i64`. This type widens the storage so signed and unsigned values both
+/// round-trip, exposing the appropriate half through [`as_signed`](#method.as_signed)
+/// and [`as_unsigned`](#method.as_unsigned).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Integer {
+    value: i128,
+}
+
+impl Integer {
+    /// Returns the value as an `i64`, or `None` if it is too large to fit.
+    pub fn as_signed(self) -> Option<i64> {
+        if self.value >= i64::min_value() as i128 && self.value <= i64::max_value() as i128 {
+            Some(self.value as i64)
+        } else {
+            None
+        }
+    }
+
+    /// Returns the value as a `u64`, or `None` if it is negative.
+    pub fn as_unsigned(self) -> Option<u64> {
+        if self.value >= 0 && self.value <= u64::max_value() as i128 {
+            Some(self.value as u64)
+        } else {
+            None
+        }
+    }
+}
+
+impl fmt::Display for Integer {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // The backing `i128` renders both the signed and unsigned ranges
+        // correctly, which is the textual form the XML writer emits.
+        self.value.fmt(f)
+    }
+}
+
+impl From<i64> for Integer {
+    fn from(value: i64) -> Integer {
+        Integer {
+            value: value as i128,
+        }
+    }
+}
+
+impl From<u64> for Integer {
+    fn from(value: u64) -> Integer {
+        Integer {
+            value: value as i128,
+        }
+    }
+}
+
+macro_rules! impl_from_small {
+    ($($t:ty),*) => {
+        $(
+            impl From<$t> for Integer {
+                fn from(value: $t) -> Integer {
+                    Integer { value: value as i128 }
+                }
+            }
+        )*
+    };
+}
+
+impl_from_small!(i8, i16, i32, u8, u16, u32);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trips_small_and_boundary_values() {
+        for &value in &[0i64, -1, 255, 256, i64::max_value()] {
+            let integer = Integer::from(value);
+            assert_eq!(integer.as_signed(), Some(value));
+            assert_eq!(integer.to_string(), value.to_string());
+        }
+    }
+
+    #[test]
+    fn round_trips_values_beyond_i64_max() {
+        for &value in &[i64::max_value() as u64 + 1, u64::max_value()] {
+            let integer = Integer::from(value);
+            assert_eq!(integer.as_unsigned(), Some(value));
+            assert_eq!(integer.as_signed(), None);
+            assert_eq!(integer.to_string(), value.to_string());
+        }
+    }
+
+    #[test]
+    fn negative_values_have_no_unsigned_representation() {
+        assert_eq!(Integer::from(-1i64).as_unsigned(), None);
+    }
+}
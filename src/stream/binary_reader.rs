@@ -0,0 +1,269 @@
+use std::borrow::Cow;
+use std::io::{Read, Seek, SeekFrom};
+use std::vec;
+
+use stream::{Event, OwnedEvent};
+use {Date, Error, Integer, Uid};
+
+/// An `Iterator` that decodes Apple's `bplist00` binary format into an
+/// `Event` stream.
+///
+/// The trailer at the end of the file points at an offset table that has to
+/// be read before any object can be located, so the whole object graph is
+/// decoded into an event buffer the first time [`next`](#method.next) is
+/// called; after that the buffered events are simply drained.
+pub struct BinaryReader<R> {
+    state: State<R>,
+}
+
+enum State<R> {
+    Unparsed(Option<R>),
+    Ready(vec::IntoIter<OwnedEvent>),
+}
+
+impl<R: Read + Seek> BinaryReader<R> {
+    pub fn new(reader: R) -> BinaryReader<R> {
+        BinaryReader {
+            state: State::Unparsed(Some(reader)),
+        }
+    }
+
+    /// Reads the trailer and offset table, then decodes the whole object
+    /// graph (starting from the top object) into a flat event stream.
+    fn parse(reader: &mut R) -> Result<Vec<OwnedEvent>, Error> {
+        let len = reader.seek(SeekFrom::End(0))?;
+
+        reader.seek(SeekFrom::Start(0))?;
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != b"bplist00" {
+            return Err(Error::invalid_data());
+        }
+
+        if len < 8 + 32 {
+            return Err(Error::invalid_data());
+        }
+        reader.seek(SeekFrom::Start(len - 32))?;
+        let mut trailer = [0u8; 32];
+        reader.read_exact(&mut trailer)?;
+
+        let offset_size = trailer[6];
+        let ref_size = trailer[7];
+        let num_objects = be_u64(&trailer[8..16]);
+        let top_object_index = be_u64(&trailer[16..24]);
+        let offset_table_start = be_u64(&trailer[24..32]);
+
+        if offset_size == 0 || offset_size > 8 || ref_size == 0 || ref_size > 8 {
+            return Err(Error::invalid_data());
+        }
+
+        reader.seek(SeekFrom::Start(offset_table_start))?;
+        let mut offsets = Vec::with_capacity(num_objects as usize);
+        for _ in 0..num_objects {
+            offsets.push(read_sized_uint(reader, offset_size)?);
+        }
+
+        let mut events = Vec::new();
+        decode_object(reader, &offsets, ref_size, top_object_index, &mut events)?;
+        Ok(events)
+    }
+}
+
+impl<R: Read + Seek> Iterator for BinaryReader<R> {
+    type Item = Result<OwnedEvent, Error>;
+
+    fn next(&mut self) -> Option<Result<OwnedEvent, Error>> {
+        let mut reader = match self.state {
+            State::Ready(ref mut events) => return events.next().map(Ok),
+            State::Unparsed(ref mut reader) => reader.take().unwrap(),
+        };
+
+        match BinaryReader::parse(&mut reader) {
+            Ok(events) => {
+                self.state = State::Ready(events.into_iter());
+                self.next()
+            }
+            Err(err) => {
+                self.state = State::Ready(Vec::new().into_iter());
+                Some(Err(err))
+            }
+        }
+    }
+}
+
+/// Decodes the object at `index` (and, for containers, everything it
+/// transitively refers to) into `events`.
+fn decode_object<R: Read + Seek>(
+    reader: &mut R,
+    offsets: &[u64],
+    ref_size: u8,
+    index: u64,
+    events: &mut Vec<OwnedEvent>,
+) -> Result<(), Error> {
+    let offset = *offsets
+        .get(index as usize)
+        .ok_or_else(Error::invalid_data)?;
+    reader.seek(SeekFrom::Start(offset))?;
+
+    let mut marker = [0u8; 1];
+    reader.read_exact(&mut marker)?;
+    let marker = marker[0];
+
+    match marker {
+        0x08 => events.push(Event::BooleanValue(false)),
+        0x09 => events.push(Event::BooleanValue(true)),
+        0x33 => {
+            let mut buf = [0u8; 8];
+            reader.read_exact(&mut buf)?;
+            let secs = f64::from_be_bytes(buf);
+            events.push(Event::DateValue(Date::from_seconds_since_plist_epoch(
+                secs,
+            )));
+        }
+        _ => match marker & 0xf0 {
+            0x10 => {
+                let size = width_from_low(marker & 0x0f)?;
+                events.push(Event::IntegerValue(read_integer(reader, size)?));
+            }
+            0x20 => {
+                let size = width_from_low(marker & 0x0f)?;
+                events.push(Event::RealValue(read_real(reader, size)?));
+            }
+            0x40 => {
+                let len = read_count(reader, marker & 0x0f)?;
+                let mut data = vec![0u8; len as usize];
+                reader.read_exact(&mut data)?;
+                events.push(Event::DataValue(Cow::Owned(data)));
+            }
+            0x50 => {
+                let len = read_count(reader, marker & 0x0f)?;
+                let mut bytes = vec![0u8; len as usize];
+                reader.read_exact(&mut bytes)?;
+                let string = String::from_utf8(bytes).map_err(|_| Error::invalid_data())?;
+                events.push(Event::StringValue(Cow::Owned(string)));
+            }
+            0x60 => {
+                let len = read_count(reader, marker & 0x0f)?;
+                let mut units = Vec::with_capacity(len as usize);
+                for _ in 0..len {
+                    let mut buf = [0u8; 2];
+                    reader.read_exact(&mut buf)?;
+                    units.push(u16::from_be_bytes(buf));
+                }
+                let string = String::from_utf16(&units).map_err(|_| Error::invalid_data())?;
+                events.push(Event::StringValue(Cow::Owned(string)));
+            }
+            0x80 => {
+                let size = (marker & 0x0f) + 1;
+                if size > 8 {
+                    return Err(Error::invalid_data());
+                }
+                let value = read_sized_uint(reader, size)?;
+                events.push(Event::UidValue(Uid::new(value)));
+            }
+            0xa0 => {
+                let len = read_count(reader, marker & 0x0f)?;
+                let refs = read_refs(reader, ref_size, len)?;
+                events.push(Event::StartArray(Some(len)));
+                for child in refs {
+                    decode_object(reader, offsets, ref_size, child, events)?;
+                }
+                events.push(Event::EndArray);
+            }
+            0xd0 => {
+                let len = read_count(reader, marker & 0x0f)?;
+                let key_refs = read_refs(reader, ref_size, len)?;
+                let value_refs = read_refs(reader, ref_size, len)?;
+                events.push(Event::StartDictionary(Some(len)));
+                for (key, value) in key_refs.into_iter().zip(value_refs) {
+                    decode_object(reader, offsets, ref_size, key, events)?;
+                    decode_object(reader, offsets, ref_size, value, events)?;
+                }
+                events.push(Event::EndDictionary);
+            }
+            _ => return Err(Error::invalid_data()),
+        },
+    }
+    Ok(())
+}
+
+/// Reads an object's length, following the marker nibble's "spill into a
+/// trailing integer object" convention used for lengths that don't fit in
+/// the low nibble (mirrors `BinaryWriter`'s `write_marker`).
+fn read_count<R: Read>(reader: &mut R, low: u8) -> Result<u64, Error> {
+    if low != 0x0f {
+        return Ok(low as u64);
+    }
+    let mut marker = [0u8; 1];
+    reader.read_exact(&mut marker)?;
+    if marker[0] & 0xf0 != 0x10 {
+        return Err(Error::invalid_data());
+    }
+    let size = width_from_low(marker[0] & 0x0f)?;
+    read_sized_uint(reader, size)
+}
+
+/// Converts a marker's low nibble into a byte width, rejecting widths wider
+/// than the 8 bytes any object body in this format actually uses.
+fn width_from_low(low: u8) -> Result<u8, Error> {
+    if low > 3 {
+        return Err(Error::invalid_data());
+    }
+    Ok(1u8 << low)
+}
+
+/// Reads `count` object references, each `ref_size` bytes wide.
+fn read_refs<R: Read>(reader: &mut R, ref_size: u8, count: u64) -> Result<Vec<u64>, Error> {
+    let mut refs = Vec::with_capacity(count as usize);
+    for _ in 0..count {
+        refs.push(read_sized_uint(reader, ref_size)?);
+    }
+    Ok(refs)
+}
+
+/// Decodes the big-endian integer body following a `0x1_` marker.
+///
+/// An 8-byte body is ambiguous between a negative `i64` and a value in
+/// `Integer`'s widened `i64::MAX..=u64::MAX` range, since both share the same
+/// raw bit pattern. This mirrors `BinaryWriter`'s encoding of that range by
+/// preferring the unsigned interpretation once the raw bits exceed
+/// `i64::MAX`, rather than silently wrapping into a negative number.
+fn read_integer<R: Read>(reader: &mut R, size: u8) -> Result<Integer, Error> {
+    let raw = read_sized_uint(reader, size)?;
+    if size < 8 {
+        return Ok(Integer::from(raw));
+    }
+    if raw <= i64::max_value() as u64 {
+        Ok(Integer::from(raw as i64))
+    } else {
+        Ok(Integer::from(raw))
+    }
+}
+
+/// Decodes the big-endian `f32`/`f64` body following a `0x2_` marker.
+fn read_real<R: Read>(reader: &mut R, size: u8) -> Result<f64, Error> {
+    match size {
+        4 => {
+            let mut buf = [0u8; 4];
+            reader.read_exact(&mut buf)?;
+            Ok(f32::from_be_bytes(buf) as f64)
+        }
+        8 => {
+            let mut buf = [0u8; 8];
+            reader.read_exact(&mut buf)?;
+            Ok(f64::from_be_bytes(buf))
+        }
+        _ => Err(Error::invalid_data()),
+    }
+}
+
+/// Reads `size` big-endian bytes (1 to 8) into a `u64`.
+fn read_sized_uint<R: Read>(reader: &mut R, size: u8) -> Result<u64, Error> {
+    let mut buf = [0u8; 8];
+    reader.read_exact(&mut buf[8 - size as usize..])?;
+    Ok(be_u64(&buf))
+}
+
+fn be_u64(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0, |acc, &b| (acc << 8) | b as u64)
+}
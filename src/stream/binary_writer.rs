@@ -0,0 +1,377 @@
+use std::io::Write;
+
+use stream::{Event, Writer};
+use {Date, Error, Integer, Uid};
+
+/// A scalar or container collected from the event stream.
+///
+/// The binary format writes an offset table and trailer after all object
+/// bodies, so the whole stream has to be buffered into an object table before
+/// anything can be emitted. Containers refer to their children by index into
+/// that table.
+enum Object {
+    Boolean(bool),
+    Integer(Integer),
+    Real(f64),
+    Date(Date),
+    String(String),
+    Data(Vec<u8>),
+    Uid(Uid),
+    Array(Vec<usize>),
+    Dictionary(Vec<usize>, Vec<usize>),
+}
+
+/// A partially collected container awaiting its matching `End*` event.
+enum Container {
+    Array(Vec<usize>),
+    Dictionary {
+        keys: Vec<usize>,
+        values: Vec<usize>,
+        expecting_key: bool,
+    },
+}
+
+/// A `Writer` that emits Apple's `bplist00` binary format.
+///
+/// Unlike [`XmlWriter`](struct.XmlWriter.html) the binary encoding cannot be
+/// produced incrementally: the trailer at the end of the file records the
+/// offset of every object, so the writer buffers the entire event stream into
+/// an object table and serializes it once the top level value is complete.
+pub struct BinaryWriter<W: Write> {
+    writer: W,
+    objects: Vec<Object>,
+    stack: Vec<Container>,
+    written: bool,
+}
+
+impl<W: Write> BinaryWriter<W> {
+    pub fn new(writer: W) -> BinaryWriter<W> {
+        BinaryWriter {
+            writer,
+            objects: Vec::new(),
+            stack: Vec::new(),
+            written: false,
+        }
+    }
+
+    /// Collects an object into the table and either attaches it to the
+    /// innermost open container or, if there is none, treats it as the top
+    /// level object and serializes the whole table.
+    fn add(&mut self, object: Object) -> Result<(), Error> {
+        let index = self.objects.len();
+        self.objects.push(object);
+        match self.stack.last_mut() {
+            Some(&mut Container::Array(ref mut refs)) => {
+                refs.push(index);
+                Ok(())
+            }
+            Some(&mut Container::Dictionary {
+                ref mut keys,
+                ref mut values,
+                ref mut expecting_key,
+            }) => {
+                if *expecting_key {
+                    keys.push(index);
+                } else {
+                    values.push(index);
+                }
+                *expecting_key = !*expecting_key;
+                Ok(())
+            }
+            None => self.serialize(index),
+        }
+    }
+
+    /// Serializes the buffered object table to the underlying writer.
+    fn serialize(&mut self, top: usize) -> Result<(), Error> {
+        if self.written {
+            // Only a single top level object is permitted per stream.
+            return Err(Error::invalid_data());
+        }
+        self.written = true;
+
+        self.writer.write_all(b"bplist00")?;
+
+        let num_objects = self.objects.len();
+        let ref_size = int_byte_width(num_objects as u64);
+
+        // Emit every object body, recording the byte offset at which it begins.
+        let mut offsets = Vec::with_capacity(num_objects);
+        let mut pos: u64 = 8;
+        for index in 0..num_objects {
+            offsets.push(pos);
+            pos += self.write_object(index, ref_size)?;
+        }
+
+        // The offset table uses the smallest integer width able to address the
+        // largest offset, which is the start of the table itself.
+        let offset_table_start = pos;
+        let offset_size = int_byte_width(offset_table_start);
+        for &offset in &offsets {
+            write_sized_uint(&mut self.writer, offset, offset_size)?;
+        }
+
+        // 32-byte trailer.
+        let mut trailer = [0u8; 32];
+        trailer[6] = offset_size;
+        trailer[7] = ref_size;
+        trailer[8..16].copy_from_slice(&(num_objects as u64).to_be_bytes());
+        trailer[16..24].copy_from_slice(&(top as u64).to_be_bytes());
+        trailer[24..32].copy_from_slice(&offset_table_start.to_be_bytes());
+        self.writer.write_all(&trailer)?;
+
+        Ok(())
+    }
+
+    /// Writes a single object body, returning the number of bytes written.
+    fn write_object(&mut self, index: usize, ref_size: u8) -> Result<u64, Error> {
+        // The object table is fully populated before serialization, so the
+        // children of a container can be looked up without borrowing `self`
+        // mutably for the whole call.
+        match self.objects[index] {
+            Object::Boolean(value) => self.write_bytes(&[if value { 0x09 } else { 0x08 }]),
+            Object::Integer(value) => write_integer(&mut self.writer, value),
+            Object::Real(value) => {
+                self.write_bytes(&[0x23])?;
+                Ok(1 + write_bytes(&mut self.writer, &value.to_be_bytes())?)
+            }
+            Object::Date(ref date) => {
+                let secs = date.to_seconds_since_plist_epoch();
+                write_bytes(&mut self.writer, &[0x33])?;
+                Ok(1 + write_bytes(&mut self.writer, &secs.to_be_bytes())?)
+            }
+            Object::String(ref string) => {
+                if string.is_ascii() {
+                    let bytes = string.as_bytes();
+                    let header = write_marker(&mut self.writer, 0x50, bytes.len())?;
+                    Ok(header + write_bytes(&mut self.writer, bytes)?)
+                } else {
+                    let utf16: Vec<u16> = string.encode_utf16().collect();
+                    let header = write_marker(&mut self.writer, 0x60, utf16.len())?;
+                    let mut written = header;
+                    for unit in utf16 {
+                        written += write_bytes(&mut self.writer, &unit.to_be_bytes())?;
+                    }
+                    Ok(written)
+                }
+            }
+            Object::Data(ref data) => {
+                let header = write_marker(&mut self.writer, 0x40, data.len())?;
+                Ok(header + write_bytes(&mut self.writer, data)?)
+            }
+            Object::Uid(uid) => {
+                let value = uid.get();
+                let size = int_byte_width(value);
+                let marker = 0x80 | (size - 1);
+                let header = write_bytes(&mut self.writer, &[marker])?;
+                Ok(header + write_sized_uint(&mut self.writer, value, size)?)
+            }
+            Object::Array(ref refs) => {
+                let refs = refs.clone();
+                let header = write_marker(&mut self.writer, 0xa0, refs.len())?;
+                let mut written = header;
+                for child in refs {
+                    written += write_sized_uint(&mut self.writer, child as u64, ref_size)?;
+                }
+                Ok(written)
+            }
+            Object::Dictionary(ref keys, ref values) => {
+                let keys = keys.clone();
+                let values = values.clone();
+                let header = write_marker(&mut self.writer, 0xd0, keys.len())?;
+                let mut written = header;
+                for key in keys {
+                    written += write_sized_uint(&mut self.writer, key as u64, ref_size)?;
+                }
+                for value in values {
+                    written += write_sized_uint(&mut self.writer, value as u64, ref_size)?;
+                }
+                Ok(written)
+            }
+        }
+    }
+
+    fn write_bytes(&mut self, bytes: &[u8]) -> Result<u64, Error> {
+        write_bytes(&mut self.writer, bytes)
+    }
+}
+
+impl<W: Write> Writer for BinaryWriter<W> {
+    fn write(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        match *event {
+            Event::StartArray(_) => {
+                self.stack.push(Container::Array(Vec::new()));
+                Ok(())
+            }
+            Event::EndArray => match self.stack.pop() {
+                Some(Container::Array(refs)) => self.add(Object::Array(refs)),
+                _ => Err(Error::invalid_data()),
+            },
+            Event::StartDictionary(_) => {
+                self.stack.push(Container::Dictionary {
+                    keys: Vec::new(),
+                    values: Vec::new(),
+                    expecting_key: true,
+                });
+                Ok(())
+            }
+            Event::EndDictionary => match self.stack.pop() {
+                Some(Container::Dictionary { keys, values, .. }) => {
+                    self.add(Object::Dictionary(keys, values))
+                }
+                _ => Err(Error::invalid_data()),
+            },
+            Event::BooleanValue(value) => self.add(Object::Boolean(value)),
+            Event::DataValue(ref value) => self.add(Object::Data(value.clone().into_owned())),
+            Event::DateValue(value) => self.add(Object::Date(value)),
+            Event::IntegerValue(value) => self.add(Object::Integer(value)),
+            Event::RealValue(value) => self.add(Object::Real(value)),
+            Event::StringValue(ref value) => self.add(Object::String(value.clone().into_owned())),
+            Event::UidValue(value) => self.add(Object::Uid(value)),
+            Event::__Nonexhaustive => unreachable!(),
+        }
+    }
+}
+
+/// The number of bytes (1, 2, 4 or 8) needed to hold `value` big-endian.
+fn int_byte_width(value: u64) -> u8 {
+    if value <= u8::max_value() as u64 {
+        1
+    } else if value <= u16::max_value() as u64 {
+        2
+    } else if value <= u32::max_value() as u64 {
+        4
+    } else {
+        8
+    }
+}
+
+/// Writes a type marker nibble plus the object length, spilling the length
+/// into a trailing integer object when it does not fit in the low nibble.
+fn write_marker<W: Write>(writer: &mut W, ty: u8, len: usize) -> Result<u64, Error> {
+    if len < 0x0f {
+        write_bytes(writer, &[ty | len as u8])
+    } else {
+        let header = write_bytes(writer, &[ty | 0x0f])?;
+        Ok(header + write_integer(writer, Integer::from(len as u64))?)
+    }
+}
+
+/// Writes an integer object (marker + big-endian body) using the smallest of
+/// the 1/2/4/8 byte widths. Negative values are always emitted as 8 bytes, as
+/// CoreFoundation does, and values in `i64::MAX..=u64::MAX` use the full 8-byte
+/// unsigned encoding.
+fn write_integer<W: Write>(writer: &mut W, value: Integer) -> Result<u64, Error> {
+    let (size, bytes) = match value.as_signed() {
+        Some(signed) if signed >= 0 => (int_byte_width(signed as u64), signed.to_be_bytes()),
+        Some(signed) => (8, signed.to_be_bytes()),
+        // Too large for `i64`; always an 8-byte unsigned value.
+        None => match value.as_unsigned() {
+            Some(unsigned) => (8, (unsigned as i64).to_be_bytes()),
+            None => return Err(Error::invalid_data()),
+        },
+    };
+    let marker = 0x10 | size.trailing_zeros() as u8;
+    let header = write_bytes(writer, &[marker])?;
+    Ok(header + write_bytes(writer, &bytes[8 - size as usize..])?)
+}
+
+/// Writes `value` using exactly `size` big-endian bytes.
+fn write_sized_uint<W: Write>(writer: &mut W, value: u64, size: u8) -> Result<u64, Error> {
+    write_bytes(writer, &value.to_be_bytes()[8 - size as usize..])
+}
+
+fn write_bytes<W: Write>(writer: &mut W, bytes: &[u8]) -> Result<u64, Error> {
+    writer.write_all(bytes)?;
+    Ok(bytes.len() as u64)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(events: &[Event<'_>]) -> Vec<u8> {
+        let mut writer = BinaryWriter::new(Vec::new());
+        for event in events {
+            writer.write(event).unwrap();
+        }
+        writer.writer
+    }
+
+    fn be_u64(bytes: &[u8]) -> u64 {
+        bytes.iter().fold(0, |acc, &b| (acc << 8) | b as u64)
+    }
+
+    /// The last 32 bytes of a `bplist00` stream: `(offset_size, ref_size,
+    /// num_objects, top_object_index, offset_table_start)`.
+    fn trailer(bytes: &[u8]) -> (u8, u8, u64, u64, u64) {
+        let t = &bytes[bytes.len() - 32..];
+        (
+            t[6],
+            t[7],
+            be_u64(&t[8..16]),
+            be_u64(&t[16..24]),
+            be_u64(&t[24..32]),
+        )
+    }
+
+    #[test]
+    fn writes_magic_and_a_one_object_trailer_for_a_scalar() {
+        let bytes = write(&[Event::IntegerValue(Integer::from(0i64))]);
+        assert_eq!(&bytes[..8], b"bplist00");
+        let (offset_size, ref_size, num_objects, top_object_index, _) = trailer(&bytes);
+        assert_eq!(num_objects, 1);
+        assert_eq!(top_object_index, 0);
+        assert_eq!(offset_size, 1);
+        assert_eq!(ref_size, 1);
+    }
+
+    #[test]
+    fn widens_the_integer_encoding_across_size_boundaries() {
+        // (value, expected body width) -- 255 still fits in one byte, 256
+        // doesn't, and a negative value is always encoded in eight bytes.
+        for &(value, width) in &[(0i64, 1u8), (255, 1), (256, 2), (-1, 8)] {
+            let bytes = write(&[Event::IntegerValue(Integer::from(value))]);
+            assert_eq!(bytes[8] & 0x0f, width.trailing_zeros() as u8);
+            assert_eq!(bytes.len(), 8 + 1 + width as usize + 1 + 32);
+        }
+    }
+
+    #[test]
+    fn writes_unsigned_values_beyond_i64_max_as_eight_bytes() {
+        for &value in &[i64::max_value() as u64 + 1, u64::max_value()] {
+            let bytes = write(&[Event::IntegerValue(Integer::from(value))]);
+            assert_eq!(bytes[8] & 0x0f, 8u8.trailing_zeros() as u8);
+            assert_eq!(&bytes[9..17], &value.to_be_bytes()[..]);
+        }
+    }
+
+    #[test]
+    fn chooses_ref_size_from_the_total_object_count() {
+        let events = std::iter::once(Event::StartArray(Some(300)))
+            .chain((0..300).map(|i| Event::IntegerValue(Integer::from(i as i64))))
+            .chain(std::iter::once(Event::EndArray))
+            .collect::<Vec<_>>();
+        let bytes = write(&events);
+        // 300 elements plus the array itself is 301 objects, which no longer
+        // fits the 1-byte ref width that a smaller stream would pick.
+        let (_, ref_size, num_objects, _, _) = trailer(&bytes);
+        assert_eq!(num_objects, 301);
+        assert_eq!(ref_size, 2);
+    }
+
+    #[test]
+    fn rejects_a_second_top_level_object() {
+        let mut writer = BinaryWriter::new(Vec::new());
+        writer.write(&Event::IntegerValue(Integer::from(1i64))).unwrap();
+        assert!(writer
+            .write(&Event::IntegerValue(Integer::from(2i64)))
+            .is_err());
+    }
+
+    #[test]
+    fn rejects_an_end_event_with_no_matching_start() {
+        let mut writer = BinaryWriter::new(Vec::new());
+        assert!(writer.write(&Event::EndArray).is_err());
+    }
+}
@@ -3,20 +3,33 @@
 mod binary_reader;
 pub use self::binary_reader::BinaryReader;
 
+mod binary_writer;
+pub use self::binary_writer::BinaryWriter;
+
+mod pos_reader;
+use self::pos_reader::PosReader;
+
 mod xml_reader;
 pub use self::xml_reader::XmlReader;
 
 mod xml_writer;
 pub use self::xml_writer::XmlWriter;
 
-use std::io::{Read, Seek, SeekFrom};
+use std::borrow::Cow;
+use std::io::{self, Cursor, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
 use std::vec;
-use {Date, Error, Value};
+use {Date, Error, Integer, Uid, Value};
 
 /// An encoding of a plist as a flat structure.
 ///
 /// Output by the event readers.
 ///
+/// String and data payloads borrow from the source `Value` where possible so
+/// that serialization does not have to copy them; use the [`OwnedEvent`] alias
+/// for the `'static` form produced by the readers.
+///
 /// Dictionary keys and values are represented as pairs of values e.g.:
 ///
 /// ```ignore rust
@@ -28,7 +41,7 @@ use {Date, Error, Value};
 /// EndDictionary
 /// ```
 #[derive(Clone, Debug, PartialEq)]
-pub enum Event {
+pub enum Event<'a> {
     // While the length of an array or dict cannot be feasably greater than max(usize) this better
     // conveys the concept of an effectively unbounded event stream.
     StartArray(Option<u64>),
@@ -38,19 +51,23 @@ pub enum Event {
     EndDictionary,
 
     BooleanValue(bool),
-    DataValue(Vec<u8>),
+    DataValue(Cow<'a, [u8]>),
     DateValue(Date),
-    IntegerValue(i64),
+    IntegerValue(Integer),
     RealValue(f64),
-    StringValue(String),
+    StringValue(Cow<'a, str>),
+    UidValue(Uid),
 
     #[doc(hidden)]
     __Nonexhaustive,
 }
 
-/// An `Event` stream returned by `Value::into_events`.
+/// An [`Event`] that owns its payloads, as produced by the event readers.
+pub type OwnedEvent = Event<'static>;
+
+/// An owning `Event` stream returned by `Value::into_events`.
 pub struct IntoEvents {
-    events: vec::IntoIter<Event>,
+    events: vec::IntoIter<OwnedEvent>,
 }
 
 impl IntoEvents {
@@ -62,7 +79,7 @@ impl IntoEvents {
         }
     }
 
-    fn new_inner(value: Value, events: &mut Vec<Event>) {
+    fn new_inner(value: Value, events: &mut Vec<OwnedEvent>) {
         match value {
             Value::Array(array) => {
                 events.push(Event::StartArray(Some(array.len() as u64)));
@@ -74,26 +91,82 @@ impl IntoEvents {
             Value::Dictionary(dict) => {
                 events.push(Event::StartDictionary(Some(dict.len() as u64)));
                 for (key, value) in dict {
-                    events.push(Event::StringValue(key));
+                    events.push(Event::StringValue(Cow::Owned(key)));
                     IntoEvents::new_inner(value, events);
                 }
                 events.push(Event::EndDictionary);
             }
             Value::Boolean(value) => events.push(Event::BooleanValue(value)),
-            Value::Data(value) => events.push(Event::DataValue(value)),
+            Value::Data(value) => events.push(Event::DataValue(Cow::Owned(value))),
             Value::Date(value) => events.push(Event::DateValue(value)),
             Value::Real(value) => events.push(Event::RealValue(value)),
             Value::Integer(value) => events.push(Event::IntegerValue(value)),
-            Value::String(value) => events.push(Event::StringValue(value)),
+            Value::String(value) => events.push(Event::StringValue(Cow::Owned(value))),
+            Value::Uid(value) => events.push(Event::UidValue(value)),
             Value::__Nonexhaustive => unreachable!(),
         }
     }
 }
 
 impl Iterator for IntoEvents {
-    type Item = Event;
+    type Item = OwnedEvent;
+
+    fn next(&mut self) -> Option<OwnedEvent> {
+        self.events.next()
+    }
+}
+
+/// A borrowing `Event` stream returned by `Value::events`.
+///
+/// Unlike [`IntoEvents`] this does not consume the `Value`; string and data
+/// payloads are borrowed from it, so serializing through a [`Writer`] avoids
+/// copying them.
+pub struct Events<'a> {
+    events: vec::IntoIter<Event<'a>>,
+}
+
+impl<'a> Events<'a> {
+    pub(crate) fn new(value: &'a Value) -> Events<'a> {
+        let mut events = Vec::new();
+        Events::new_inner(value, &mut events);
+        Events {
+            events: events.into_iter(),
+        }
+    }
+
+    fn new_inner(value: &'a Value, events: &mut Vec<Event<'a>>) {
+        match *value {
+            Value::Array(ref array) => {
+                events.push(Event::StartArray(Some(array.len() as u64)));
+                for value in array {
+                    Events::new_inner(value, events);
+                }
+                events.push(Event::EndArray);
+            }
+            Value::Dictionary(ref dict) => {
+                events.push(Event::StartDictionary(Some(dict.len() as u64)));
+                for (key, value) in dict {
+                    events.push(Event::StringValue(Cow::Borrowed(key)));
+                    Events::new_inner(value, events);
+                }
+                events.push(Event::EndDictionary);
+            }
+            Value::Boolean(value) => events.push(Event::BooleanValue(value)),
+            Value::Data(ref value) => events.push(Event::DataValue(Cow::Borrowed(value))),
+            Value::Date(value) => events.push(Event::DateValue(value)),
+            Value::Real(value) => events.push(Event::RealValue(value)),
+            Value::Integer(value) => events.push(Event::IntegerValue(value)),
+            Value::String(ref value) => events.push(Event::StringValue(Cow::Borrowed(value))),
+            Value::Uid(value) => events.push(Event::UidValue(value)),
+            Value::__Nonexhaustive => unreachable!(),
+        }
+    }
+}
+
+impl<'a> Iterator for Events<'a> {
+    type Item = Event<'a>;
 
-    fn next(&mut self) -> Option<Event> {
+    fn next(&mut self) -> Option<Event<'a>> {
         self.events.next()
     }
 }
@@ -102,8 +175,8 @@ pub struct Reader<R: Read + Seek>(ReaderInner<R>);
 
 enum ReaderInner<R: Read + Seek> {
     Uninitialized(Option<R>),
-    Xml(XmlReader<R>),
-    Binary(BinaryReader<R>),
+    Xml(XmlReader<PosReader<R>>, Arc<AtomicU64>),
+    Binary(BinaryReader<PosReader<R>>, Arc<AtomicU64>),
 }
 
 impl<R: Read + Seek> Reader<R> {
@@ -112,28 +185,76 @@ impl<R: Read + Seek> Reader<R> {
     }
 
     fn is_binary(reader: &mut R) -> Result<bool, Error> {
-        reader.seek(SeekFrom::Start(0))?;
-        let mut magic = [0; 8];
-        reader.read_exact(&mut magic)?;
-        reader.seek(SeekFrom::Start(0))?;
+        let sniff = |reader: &mut R| -> Result<bool, Error> {
+            reader.seek(SeekFrom::Start(0))?;
+            let mut magic = [0; 8];
+            reader.read_exact(&mut magic)?;
+            reader.seek(SeekFrom::Start(0))?;
 
-        Ok(&magic == b"bplist00")
+            Ok(&magic == b"bplist00")
+        };
+        sniff(reader).map_err(|err| err.with_position_if_unset(0))
+    }
+}
+
+/// A boxed event reader produced by [`from_read`] for sources that cannot
+/// `Seek`.
+pub type BoxedReader = Box<dyn Iterator<Item = Result<OwnedEvent, Error>>>;
+
+/// Detects and parses a plist from a plain `Read` source that need not
+/// implement `Seek`, such as a socket or stdin.
+///
+/// The first eight bytes are buffered to sniff the `bplist00` magic. XML
+/// can then be streamed directly from the buffered prefix chained to the
+/// rest of the input; the binary reader still needs random access for the
+/// trailer and offset table, so for that case the whole input is read into
+/// memory first.
+pub fn from_read<R: Read + 'static>(mut reader: R) -> Result<BoxedReader, Error> {
+    let mut magic = [0; 8];
+    let prefix = fill_buf(&mut reader, &mut magic)
+        .map_err(|err| Error::from(err).with_position_if_unset(0))?;
+
+    if &magic[..prefix] == b"bplist00" {
+        let mut buf = magic.to_vec();
+        reader
+            .read_to_end(&mut buf)
+            .map_err(|err| Error::from(err).with_position_if_unset(prefix as u64))?;
+        let inner = BinaryReader::new(Cursor::new(buf));
+        Ok(Box::new(inner))
+    } else {
+        let chained = Cursor::new(magic[..prefix].to_vec()).chain(reader);
+        let inner = XmlReader::new(chained);
+        Ok(Box::new(inner))
     }
 }
 
 impl<R: Read + Seek> Iterator for Reader<R> {
-    type Item = Result<Event, Error>;
+    type Item = Result<OwnedEvent, Error>;
 
-    fn next(&mut self) -> Option<Result<Event, Error>> {
+    fn next(&mut self) -> Option<Result<OwnedEvent, Error>> {
         let mut reader = match self.0 {
-            ReaderInner::Xml(ref mut parser) => return parser.next(),
-            ReaderInner::Binary(ref mut parser) => return parser.next(),
+            ReaderInner::Xml(ref mut parser, ref pos) => {
+                return parser.next().map(|result| {
+                    result.map_err(|err| err.with_position_if_unset(pos.load(Ordering::Relaxed)))
+                });
+            }
+            ReaderInner::Binary(ref mut parser, ref pos) => {
+                return parser.next().map(|result| {
+                    result.map_err(|err| err.with_position_if_unset(pos.load(Ordering::Relaxed)))
+                });
+            }
             ReaderInner::Uninitialized(ref mut reader) => reader.take().unwrap(),
         };
 
         let event_reader = match Reader::is_binary(&mut reader) {
-            Ok(true) => ReaderInner::Binary(BinaryReader::new(reader)),
-            Ok(false) => ReaderInner::Xml(XmlReader::new(reader)),
+            Ok(true) => {
+                let (pos_reader, pos) = PosReader::new(reader);
+                ReaderInner::Binary(BinaryReader::new(pos_reader), pos)
+            }
+            Ok(false) => {
+                let (pos_reader, pos) = PosReader::new(reader);
+                ReaderInner::Xml(XmlReader::new(pos_reader), pos)
+            }
             Err(err) => {
                 ::std::mem::replace(&mut self.0, ReaderInner::Uninitialized(Some(reader)));
                 return Some(Err(err));
@@ -146,7 +267,137 @@ impl<R: Read + Seek> Iterator for Reader<R> {
     }
 }
 
+/// Reads up to `buf.len()` bytes, returning the number actually read.
+///
+/// Unlike `read_exact` this tolerates a source shorter than the buffer (a
+/// short input simply is not a binary plist) and copes with readers that
+/// return fewer bytes than requested per call.
+fn fill_buf<R: Read>(reader: &mut R, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..])? {
+            0 => break,
+            read => filled += read,
+        }
+    }
+    Ok(filled)
+}
+
 /// Supports writing event streams in different plist encodings.
 pub trait Writer {
-    fn write(&mut self, event: &Event) -> Result<(), Error>;
+    fn write(&mut self, event: &Event<'_>) -> Result<(), Error>;
+}
+
+/// The line ending written between elements by an [`XmlWriter`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LineEnding {
+    /// A single line feed, `\n`, as used on Unix.
+    Lf,
+    /// A carriage return followed by a line feed, `\r\n`, as used on Windows.
+    CrLf,
+}
+
+impl LineEnding {
+    fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+}
+
+/// Options controlling the formatting of XML produced by
+/// `XmlWriter::new_with_options`.
+///
+/// The defaults match the output of `XmlWriter::new`: a tab per indent level,
+/// a leading `<?xml?>` declaration and `DOCTYPE`, and Unix line endings. Tweak
+/// them to byte-match `plutil`/Xcode conventions, or disable indentation and
+/// the header entirely for compact, easily diffed plists.
+#[derive(Clone, Debug)]
+pub struct XmlWriteOptions {
+    indent_str: Cow<'static, str>,
+    write_header: bool,
+    line_ending: LineEnding,
+}
+
+impl XmlWriteOptions {
+    /// The string used for a single level of indentation.
+    ///
+    /// Passing an empty string, together with `line_ending(LineEnding::Lf)`,
+    /// produces single-line-per-element compact output.
+    pub fn indent_string<S: Into<Cow<'static, str>>>(mut self, indent_str: S) -> Self {
+        self.indent_str = indent_str.into();
+        self
+    }
+
+    /// Whether to emit the leading `<?xml?>` declaration and `DOCTYPE`.
+    pub fn write_header(mut self, write_header: bool) -> Self {
+        self.write_header = write_header;
+        self
+    }
+
+    /// The line ending written between elements.
+    pub fn line_ending(mut self, line_ending: LineEnding) -> Self {
+        self.line_ending = line_ending;
+        self
+    }
+
+    pub(crate) fn indent_str(&self) -> &str {
+        &self.indent_str
+    }
+
+    pub(crate) fn header(&self) -> bool {
+        self.write_header
+    }
+
+    pub(crate) fn eol(&self) -> &'static str {
+        self.line_ending.as_str()
+    }
+}
+
+impl Default for XmlWriteOptions {
+    fn default() -> Self {
+        XmlWriteOptions {
+            indent_str: Cow::Borrowed("\t"),
+            write_header: true,
+            line_ending: LineEnding::Lf,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_the_byte_offset_at_which_parsing_failed() {
+        // Valid magic but far too short to hold a trailer, so `BinaryReader`
+        // fails while reading it; the position should reflect how far the
+        // underlying `PosReader` had actually advanced, not just "unknown".
+        let mut data = b"bplist00".to_vec();
+        data.extend_from_slice(&[0u8; 10]);
+
+        let err = Reader::new(Cursor::new(data)).last().unwrap().unwrap_err();
+        assert_eq!(err.position(), Some(8));
+    }
+
+    #[test]
+    fn from_read_picks_the_binary_reader_for_bplist00_prefixed_input() {
+        let mut data = b"bplist00".to_vec();
+        data.extend_from_slice(&[0u8; 10]);
+
+        let err = from_read(Cursor::new(data)).unwrap().last().unwrap();
+        // The truncated trailer is still detected, proving the binary path
+        // (not the XML path) was the one that ran.
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn from_read_falls_back_to_the_xml_reader_for_non_binary_input() {
+        // Shorter than the 8-byte magic sniff buffer, so `fill_buf` returns a
+        // short read rather than the binary magic, and `from_read` must still
+        // hand the buffered prefix off to the XML path instead of failing.
+        let reader = from_read(Cursor::new(b"<pl".to_vec())).unwrap();
+        assert!(reader.last().is_some());
+    }
 }
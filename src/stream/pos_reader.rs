@@ -0,0 +1,47 @@
+use std::io::{self, Read, Seek, SeekFrom};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+/// A `Read + Seek` adapter that tracks the current byte offset into the
+/// underlying reader, so callers holding on to the shared counter can tag
+/// errors raised elsewhere (e.g. deep inside a sub-reader's parser) with the
+/// position at which they occurred.
+///
+/// The counter is an `Arc<AtomicU64>` rather than an `Rc<Cell<u64>>` so that
+/// `Reader<R>` stays `Send` when `R: Send`; the counter is only ever touched
+/// from the thread driving the reader, so `Relaxed` ordering is enough.
+pub struct PosReader<R> {
+    inner: R,
+    pos: Arc<AtomicU64>,
+}
+
+impl<R> PosReader<R> {
+    /// Wraps `inner`, returning the adapter along with a handle that always
+    /// reflects its current position.
+    pub fn new(inner: R) -> (PosReader<R>, Arc<AtomicU64>) {
+        let pos = Arc::new(AtomicU64::new(0));
+        (
+            PosReader {
+                inner,
+                pos: pos.clone(),
+            },
+            pos,
+        )
+    }
+}
+
+impl<R: Read> Read for PosReader<R> {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let read = self.inner.read(buf)?;
+        self.pos.fetch_add(read as u64, Ordering::Relaxed);
+        Ok(read)
+    }
+}
+
+impl<R: Seek> Seek for PosReader<R> {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        let new_pos = self.inner.seek(pos)?;
+        self.pos.store(new_pos, Ordering::Relaxed);
+        Ok(new_pos)
+    }
+}
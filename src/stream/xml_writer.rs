@@ -0,0 +1,290 @@
+use std::io::Write;
+
+use stream::{Event, LineEnding, Writer, XmlWriteOptions};
+use Error;
+
+static XML_DECLARATION: &str = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>";
+static DOCTYPE: &str = "<!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \
+                        \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">";
+
+/// Tracks whether the innermost open container is a dictionary, and if so
+/// whether the next string is a key or a value.
+enum Container {
+    Array,
+    Dictionary { expecting_key: bool },
+}
+
+/// A `Writer` that emits the XML plist encoding.
+///
+/// Formatting is governed by [`XmlWriteOptions`]; [`new`](#method.new) uses the
+/// defaults while [`new_with_options`](#method.new_with_options) takes an
+/// explicit configuration.
+pub struct XmlWriter<W: Write> {
+    writer: W,
+    options: XmlWriteOptions,
+    stack: Vec<Container>,
+    started: bool,
+}
+
+impl<W: Write> XmlWriter<W> {
+    pub fn new(writer: W) -> XmlWriter<W> {
+        XmlWriter::new_with_options(writer, XmlWriteOptions::default())
+    }
+
+    pub fn new_with_options(writer: W, options: XmlWriteOptions) -> XmlWriter<W> {
+        XmlWriter {
+            writer,
+            options,
+            stack: Vec::new(),
+            started: false,
+        }
+    }
+
+    /// Emits the `<?xml?>` declaration and `DOCTYPE` before the first
+    /// element, if the options request a header, then the opening `<plist>`
+    /// tag, which is always written so the document has a single root.
+    fn write_header(&mut self) -> Result<(), Error> {
+        if self.options.header() {
+            self.write_line(0, XML_DECLARATION)?;
+            self.write_line(0, DOCTYPE)?;
+        }
+        self.write_line(0, "<plist version=\"1.0\">")?;
+        Ok(())
+    }
+
+    /// Writes `content` indented for the given depth and followed by the
+    /// configured line ending.
+    fn write_line(&mut self, depth: usize, content: &str) -> Result<(), Error> {
+        for _ in 0..depth {
+            self.writer.write_all(self.options.indent_str().as_bytes())?;
+        }
+        self.writer.write_all(content.as_bytes())?;
+        self.writer.write_all(self.options.eol().as_bytes())?;
+        Ok(())
+    }
+
+    /// Writes a single leaf element, e.g. `<integer>5</integer>`, at the
+    /// current depth.
+    fn write_element(&mut self, name: &str, text: &str) -> Result<(), Error> {
+        let depth = self.stack.len();
+        let line = format!("<{0}>{1}</{0}>", name, escape(text));
+        self.write_line(depth, &line)
+    }
+
+    /// Records that a complete value has just been written so that a following
+    /// dictionary entry is interpreted as a key.
+    fn after_value(&mut self) {
+        if let Some(&mut Container::Dictionary {
+            ref mut expecting_key,
+        }) = self.stack.last_mut()
+        {
+            *expecting_key = true;
+        }
+    }
+}
+
+impl<W: Write> Writer for XmlWriter<W> {
+    fn write(&mut self, event: &Event<'_>) -> Result<(), Error> {
+        if !self.started {
+            self.started = true;
+            self.write_header()?;
+        }
+
+        match *event {
+            Event::StartArray(_) => {
+                let depth = self.stack.len();
+                self.write_line(depth, "<array>")?;
+                self.stack.push(Container::Array);
+                Ok(())
+            }
+            Event::EndArray => match self.stack.pop() {
+                Some(Container::Array) => {
+                    let depth = self.stack.len();
+                    self.write_line(depth, "</array>")?;
+                    self.after_value();
+                    Ok(())
+                }
+                _ => Err(Error::invalid_data()),
+            },
+            Event::StartDictionary(_) => {
+                let depth = self.stack.len();
+                self.write_line(depth, "<dict>")?;
+                self.stack.push(Container::Dictionary {
+                    expecting_key: true,
+                });
+                Ok(())
+            }
+            Event::EndDictionary => match self.stack.pop() {
+                Some(Container::Dictionary { .. }) => {
+                    let depth = self.stack.len();
+                    self.write_line(depth, "</dict>")?;
+                    self.after_value();
+                    Ok(())
+                }
+                _ => Err(Error::invalid_data()),
+            },
+            Event::BooleanValue(value) => {
+                let depth = self.stack.len();
+                self.write_line(depth, if value { "<true/>" } else { "<false/>" })?;
+                self.after_value();
+                Ok(())
+            }
+            Event::DataValue(ref value) => {
+                self.write_element("data", &base64_encode(value))?;
+                self.after_value();
+                Ok(())
+            }
+            Event::DateValue(value) => {
+                self.write_element("date", &value.to_string())?;
+                self.after_value();
+                Ok(())
+            }
+            Event::IntegerValue(value) => {
+                self.write_element("integer", &value.to_string())?;
+                self.after_value();
+                Ok(())
+            }
+            Event::RealValue(value) => {
+                self.write_element("real", &value.to_string())?;
+                self.after_value();
+                Ok(())
+            }
+            Event::StringValue(ref value) => {
+                let is_key = match self.stack.last() {
+                    Some(&Container::Dictionary { expecting_key }) => expecting_key,
+                    _ => false,
+                };
+                if is_key {
+                    self.write_element("key", value)?;
+                    if let Some(&mut Container::Dictionary {
+                        ref mut expecting_key,
+                    }) = self.stack.last_mut()
+                    {
+                        *expecting_key = false;
+                    }
+                } else {
+                    self.write_element("string", value)?;
+                    self.after_value();
+                }
+                Ok(())
+            }
+            Event::UidValue(value) => {
+                // The XML encoding has no dedicated UID element; Apple renders
+                // `CF$UID` objects as a single-entry dictionary, so match that,
+                // writing each line the same way `StartDictionary`/`EndDictionary`
+                // do so indentation and line endings stay consistent.
+                let depth = self.stack.len();
+                self.write_line(depth, "<dict>")?;
+                self.write_line(depth + 1, "<key>CF$UID</key>")?;
+                let integer = format!("<integer>{}</integer>", value.get());
+                self.write_line(depth + 1, &integer)?;
+                self.write_line(depth, "</dict>")?;
+                self.after_value();
+                Ok(())
+            }
+            Event::__Nonexhaustive => unreachable!(),
+        }
+    }
+}
+
+impl<W: Write> Drop for XmlWriter<W> {
+    fn drop(&mut self) {
+        if self.started {
+            let _ = self.write_line(0, "</plist>");
+        }
+    }
+}
+
+/// Escapes the characters that are not permitted in XML element text.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            other => escaped.push(other),
+        }
+    }
+    escaped
+}
+
+/// Standard (RFC 4648) base64 used by the `<data>` element.
+fn base64_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] =
+        b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut out = String::with_capacity((bytes.len() + 2) / 3 * 4);
+    for chunk in bytes.chunks(3) {
+        let b0 = chunk[0] as usize;
+        let b1 = *chunk.get(1).unwrap_or(&0) as usize;
+        let b2 = *chunk.get(2).unwrap_or(&0) as usize;
+        out.push(ALPHABET[b0 >> 2] as char);
+        out.push(ALPHABET[(b0 << 4 | b1 >> 4) & 0x3f] as char);
+        out.push(if chunk.len() > 1 {
+            ALPHABET[(b1 << 2 | b2 >> 6) & 0x3f] as char
+        } else {
+            '='
+        });
+        out.push(if chunk.len() > 2 {
+            ALPHABET[b2 & 0x3f] as char
+        } else {
+            '='
+        });
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write_with(options: XmlWriteOptions, events: &[Event<'_>]) -> String {
+        let mut buf = Vec::new();
+        {
+            let mut writer = XmlWriter::new_with_options(&mut buf, options);
+            for event in events {
+                writer.write(event).unwrap();
+            }
+        }
+        String::from_utf8(buf).unwrap()
+    }
+
+    #[test]
+    fn omits_the_declaration_and_doctype_when_the_header_is_disabled() {
+        let options = XmlWriteOptions::default().write_header(false);
+        let output = write_with(options, &[Event::BooleanValue(true)]);
+
+        assert!(!output.contains("<?xml"));
+        assert!(!output.contains("<!DOCTYPE"));
+        assert!(output.starts_with("<plist version=\"1.0\">\n"));
+        assert!(output.contains("<true/>\n"));
+    }
+
+    #[test]
+    fn indents_nested_elements_with_the_configured_string() {
+        let options = XmlWriteOptions::default()
+            .write_header(false)
+            .indent_string("  ");
+        let output = write_with(
+            options,
+            &[
+                Event::StartArray(Some(1)),
+                Event::BooleanValue(true),
+                Event::EndArray,
+            ],
+        );
+
+        assert!(output.contains("<array>\n  <true/>\n</array>\n"));
+    }
+
+    #[test]
+    fn writes_crlf_line_endings_when_requested() {
+        let options = XmlWriteOptions::default()
+            .write_header(false)
+            .line_ending(LineEnding::CrLf);
+        let output = write_with(options, &[Event::BooleanValue(true)]);
+
+        assert!(output.contains("<plist version=\"1.0\">\r\n"));
+        assert!(output.contains("<true/>\r\n"));
+    }
+}
@@ -0,0 +1,20 @@
+/// A unique identifier used by `NSKeyedArchiver` to refer to other objects in
+/// an archived Cocoa object graph.
+///
+/// Stored in the binary plist format as a UID object (marker `0x8_`). The XML
+/// encoding has no dedicated UID element; `XmlWriter` writes one as a
+/// single-entry dictionary whose `CF$UID` key maps to the integer value.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct Uid {
+    value: u64,
+}
+
+impl Uid {
+    pub fn new(value: u64) -> Uid {
+        Uid { value }
+    }
+
+    pub fn get(&self) -> u64 {
+        self.value
+    }
+}
@@ -0,0 +1,35 @@
+use std::collections::BTreeMap;
+
+use stream::{Events, IntoEvents};
+use {Date, Integer, Uid};
+
+/// A parsed plist value.
+#[derive(Clone, Debug)]
+pub enum Value {
+    Array(Vec<Value>),
+    Dictionary(BTreeMap<String, Value>),
+    Boolean(bool),
+    Data(Vec<u8>),
+    Date(Date),
+    Real(f64),
+    Integer(Integer),
+    String(String),
+    Uid(Uid),
+
+    #[doc(hidden)]
+    __Nonexhaustive,
+}
+
+impl Value {
+    /// Returns an event stream that borrows its string and data payloads from
+    /// this value, so serializing it through a `Writer` does not have to
+    /// copy them.
+    pub fn events(&self) -> Events<'_> {
+        Events::new(self)
+    }
+
+    /// Consumes this value, returning an owning event stream.
+    pub fn into_events(self) -> IntoEvents {
+        IntoEvents::new(self)
+    }
+}